@@ -0,0 +1,23 @@
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read tsm file: {reason}")]
+    ReadTsmErr { reason: String },
+
+    #[error("not a recognized file: byte {offset} is {found:#04x}, expected {expected:#04x}")]
+    BadMagic { offset: usize, expected: u8, found: u8 },
+
+    #[error(
+        "file was mangled in transfer: byte {offset} is {found:#04x}, expected {expected:#04x}"
+    )]
+    TruncatedTransfer { offset: usize, expected: u8, found: u8 },
+
+    #[error("unsupported file format version: {found}")]
+    UnsupportedVersion { found: u8 },
+
+    #[error(transparent)]
+    IO(#[from] io::Error),
+}
@@ -0,0 +1,76 @@
+use crate::error::{Error, Result};
+
+// A PNG-style signature, written as the first 8 bytes of every TSM file;
+// record files and the summary do not carry it. The plain 4-byte magic + 1-byte
+// version this replaces could not tell "not one of our files" apart from
+// "one of our files, mangled by a text-mode transfer or truncated partway
+// through" -- all three show up as a bad first byte. Each byte here is
+// chosen the way PNG's is, to make a specific corruption visible:
+//
+//   byte 0   non-ASCII, so tools that assume a text file notice immediately
+//   byte 1-3 "TSM", a human-readable tag for anyone staring at a hex dump
+//   byte 4-5 CR LF, caught and mangled by CRLF/LF line-ending translation
+//   byte 6   DOS EOF (0x1A), truncates naive "copy until EOF" tools
+//   byte 7   LF, catches a lone-CR or lone-LF translation the CRLF pair missed
+const SIGNATURE: [u8; 8] = [0x89, b'T', b'S', b'M', 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub(crate) const FILE_HEADER_LEN: u64 = 13;
+pub(crate) const CURRENT_VERSION: u8 = 1;
+
+/// Versioned header written at the start of every TSM file (record files
+/// and the summary do not use it), replacing a bare magic + version with one that can tell a
+/// foreign file, a corrupted transfer, and a truncated file apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TsmHeader {
+    pub signature: [u8; 8],
+    pub version: u8,
+    /// Bitset of format extensions a reader may not understand yet. Readers
+    /// ignore flags they don't recognize, so new features can be added
+    /// without breaking old binaries reading new files.
+    pub feature_flags: u32,
+}
+
+impl TsmHeader {
+    pub fn new(feature_flags: u32) -> Self {
+        Self { signature: SIGNATURE, version: CURRENT_VERSION, feature_flags }
+    }
+
+    pub fn encode(&self) -> [u8; FILE_HEADER_LEN as usize] {
+        let mut buf = [0_u8; FILE_HEADER_LEN as usize];
+        buf[0..8].copy_from_slice(&self.signature);
+        buf[8] = self.version;
+        buf[9..13].copy_from_slice(&self.feature_flags.to_be_bytes());
+        buf
+    }
+
+    /// Decodes and validates a header, distinguishing "not one of our
+    /// files" ([`Error::BadMagic`]), "mangled in transfer"
+    /// ([`Error::TruncatedTransfer`]) and "unsupported version"
+    /// ([`Error::UnsupportedVersion`]) so operators know which problem
+    /// they're looking at. A buffer shorter than the header is reported as
+    /// a truncated header ([`Error::ReadTsmErr`]).
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < FILE_HEADER_LEN as usize {
+            return Err(Error::ReadTsmErr {
+                reason: format!("truncated header: {} of {} bytes", buf.len(), FILE_HEADER_LEN),
+            });
+        }
+        for (offset, (&expected, &found)) in SIGNATURE[0..4].iter().zip(buf[0..4].iter()).enumerate()
+        {
+            if expected != found {
+                return Err(Error::BadMagic { offset, expected, found });
+            }
+        }
+        for (i, (&expected, &found)) in SIGNATURE[4..8].iter().zip(buf[4..8].iter()).enumerate() {
+            if expected != found {
+                return Err(Error::TruncatedTransfer { offset: 4 + i, expected, found });
+            }
+        }
+        let version = buf[8];
+        if version != CURRENT_VERSION {
+            return Err(Error::UnsupportedVersion { found: version });
+        }
+        let feature_flags = u32::from_be_bytes(buf[9..13].try_into().unwrap());
+        Ok(Self { signature: SIGNATURE, version, feature_flags })
+    }
+}
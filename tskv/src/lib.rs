@@ -6,6 +6,7 @@
 mod compute;
 mod direct_io;
 mod error;
+mod file_header;
 mod file_manager;
 mod file_utils;
 mod forward_index;
@@ -26,6 +27,7 @@ use tokio::sync::oneshot;
 
 pub use direct_io::*;
 pub use error::*;
+pub use file_header::*;
 pub use file_manager::*;
 pub use file_utils::*;
 pub use kv_option::Options;
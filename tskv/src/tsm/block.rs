@@ -0,0 +1,163 @@
+use crate::error::{Error, Result};
+use crate::tsm::coders;
+
+/// The type of value stored in a [`DataBlock`], persisted alongside each
+/// series' blocks in the TSM index so a reader can dispatch to the right
+/// codec without touching the data itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Float,
+    Integer,
+    Unsigned,
+    Boolean,
+    String,
+}
+
+impl From<FieldType> for u8 {
+    fn from(typ: FieldType) -> Self {
+        match typ {
+            FieldType::Float => 0,
+            FieldType::Integer => 1,
+            FieldType::Unsigned => 2,
+            FieldType::Boolean => 3,
+            FieldType::String => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for FieldType {
+    type Error = Error;
+
+    fn try_from(typ: u8) -> Result<Self> {
+        match typ {
+            0 => Ok(FieldType::Float),
+            1 => Ok(FieldType::Integer),
+            2 => Ok(FieldType::Unsigned),
+            3 => Ok(FieldType::Boolean),
+            4 => Ok(FieldType::String),
+            _ => Err(Error::ReadTsmErr { reason: format!("unknown field type: {}", typ) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StrCell {
+    pub ts: i64,
+    pub val: Vec<u8>,
+}
+
+/// An in-memory, single-series, single-field run of values, as assembled by
+/// the write path before being split into on-disk blocks by
+/// [`crate::tsm::TsmBlockWriter`].
+#[derive(Debug, Clone)]
+pub enum DataBlock {
+    Float { ts: Vec<i64>, val: Vec<f64> },
+    Int { ts: Vec<i64>, val: Vec<i64> },
+    Unsigned { ts: Vec<i64>, val: Vec<u64> },
+    Bool { ts: Vec<i64>, val: Vec<bool> },
+    Str { ts: Vec<i64>, val: Vec<Vec<u8>> },
+}
+
+impl DataBlock {
+    pub fn filed_type(&self) -> FieldType {
+        match self {
+            DataBlock::Float { .. } => FieldType::Float,
+            DataBlock::Int { .. } => FieldType::Integer,
+            DataBlock::Unsigned { .. } => FieldType::Unsigned,
+            DataBlock::Bool { .. } => FieldType::Boolean,
+            DataBlock::Str { .. } => FieldType::String,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            DataBlock::Float { ts, .. } => ts.len(),
+            DataBlock::Int { ts, .. } => ts.len(),
+            DataBlock::Unsigned { ts, .. } => ts.len(),
+            DataBlock::Bool { ts, .. } => ts.len(),
+            DataBlock::Str { ts, .. } => ts.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn timestamps(&self) -> &[i64] {
+        match self {
+            DataBlock::Float { ts, .. } => ts,
+            DataBlock::Int { ts, .. } => ts,
+            DataBlock::Unsigned { ts, .. } => ts,
+            DataBlock::Bool { ts, .. } => ts,
+            DataBlock::Str { ts, .. } => ts,
+        }
+    }
+
+    /// Returns the `[min, max]` timestamp range of the values in `[start, end)`.
+    pub fn time_range(&self, start: usize, end: usize) -> (i64, i64) {
+        let ts = &self.timestamps()[start..end];
+        let min_ts = ts.iter().min().copied().unwrap_or_default();
+        let max_ts = ts.iter().max().copied().unwrap_or_default();
+        (min_ts, max_ts)
+    }
+
+    /// Encodes the values in `[start, end)` into a pair of `(ts_buf, data_buf)`
+    /// byte buffers, ready to be CRC'd and appended to a TSM file. Each
+    /// buffer carries its own codec id so a reader can dispatch without
+    /// consulting anything else (see `tsm::coders`).
+    pub fn encode(&mut self, start: usize, end: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut ts_buf = Vec::new();
+        coders::timestamp::encode(&self.timestamps()[start..end], &mut ts_buf);
+
+        let mut data_buf = Vec::new();
+        match self {
+            DataBlock::Float { val, .. } => coders::float::encode(&val[start..end], &mut data_buf),
+            DataBlock::Int { val, .. } => coders::integer::encode(&val[start..end], &mut data_buf),
+            DataBlock::Unsigned { val, .. } => {
+                coders::unsigned::encode(&val[start..end], &mut data_buf)
+            },
+            DataBlock::Bool { val, .. } => coders::boolean::encode(&val[start..end], &mut data_buf),
+            DataBlock::Str { val, .. } => {
+                let slices: Vec<&[u8]> = val[start..end].iter().map(|v| &v[..]).collect();
+                coders::string::encode(&slices, &mut data_buf);
+            },
+        }
+        Ok((ts_buf, data_buf))
+    }
+
+    /// Decodes a `(ts_buf, data_buf)` pair previously produced by
+    /// [`DataBlock::encode`] back into a `DataBlock` of the given type.
+    pub fn decode(typ: FieldType, ts_buf: &[u8], data_buf: &[u8]) -> Result<DataBlock> {
+        let count = coders::timestamp::decode_count(ts_buf)?;
+        let ts = coders::timestamp::decode(ts_buf, count)?;
+
+        let block = match typ {
+            FieldType::Float => {
+                DataBlock::Float { val: coders::float::decode(data_buf, count)?, ts }
+            },
+            FieldType::Integer => {
+                DataBlock::Int { val: coders::integer::decode(data_buf, count)?, ts }
+            },
+            FieldType::Unsigned => {
+                DataBlock::Unsigned { val: coders::unsigned::decode(data_buf, count)?, ts }
+            },
+            FieldType::Boolean => {
+                DataBlock::Bool { val: coders::boolean::decode(data_buf, count)?, ts }
+            },
+            FieldType::String => DataBlock::Str { val: coders::string::decode(data_buf)?, ts },
+        };
+        Ok(block)
+    }
+}
+
+/// A single on-disk block's index entry: where it lives in the file and the
+/// time range it covers, as recorded by [`crate::tsm::TsmIndexBuilder`].
+#[derive(Debug, Clone, Copy)]
+pub struct FileBlock {
+    pub min_ts: i64,
+    pub max_ts: i64,
+    pub offset: u64,
+    pub filed_type: FieldType,
+    pub size: u64,
+    pub reader_idx: usize,
+}
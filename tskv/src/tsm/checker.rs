@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::sync::Arc;
+
+use super::writer::HEADER_LEN;
+use crate::error::{Error, Result};
+use crate::tsm::{DataBlock, FieldType, FileBlock};
+use crate::{IoEngine, TsmHeader};
+
+/// A single problem found while checking a TSM file. The file may still be
+/// partially usable even when these are present; see [`crate::tsm::TsmRepairer`].
+#[derive(Debug, PartialEq)]
+pub enum BlockIssue {
+    /// The CRC stored next to a block does not match its bytes.
+    CrcMismatch { offset: u64 },
+    /// The index claims a block at `offset` but the bytes there could not be
+    /// decoded as a block at all (dangling index entry). Also reported for a
+    /// scanned block at `offset` whose type byte is not a known [`FieldType`].
+    Unreadable { offset: u64, reason: String },
+    /// The block decoded fine, but its time range disagrees with what the
+    /// index recorded for it.
+    TimeRangeMismatch { offset: u64, index_range: (i64, i64), actual_range: (i64, i64) },
+    /// The block at `offset` belongs to a different series than the index
+    /// entry pointing at it -- e.g. an index entry shuffled onto the wrong
+    /// series.
+    FidMismatch { offset: u64, index_fid: u64, block_fid: u64 },
+}
+
+/// Outcome of [`TsmChecker::check`]: how many blocks were examined and
+/// whatever went wrong along the way.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub blocks_checked: usize,
+    pub issues: Vec<BlockIssue>,
+}
+
+impl CheckReport {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// A block found while sequentially scanning the blocks section of a TSM
+/// file, along with whether its CRCs verified.
+pub(crate) struct ScannedBlock {
+    pub offset: u64,
+    pub fid: u64,
+    /// `None` if the on-disk type tag did not decode to a known
+    /// [`FieldType`] -- a corrupt (not truncated) byte, as opposed to a
+    /// truncated file, which `scan_blocks` stops on instead of recording.
+    pub filed_type: Option<FieldType>,
+    pub ts_buf: Vec<u8>,
+    pub data_buf: Vec<u8>,
+    pub crc_ok: bool,
+}
+
+/// Validates a TSM file's header, block CRCs and index without modifying it.
+/// Use [`TsmRepairer`](super::TsmRepairer) to rebuild a file once problems
+/// are found here.
+pub struct TsmChecker {
+    engine: Arc<dyn IoEngine>,
+    pos: u64,
+}
+
+impl TsmChecker {
+    pub fn new(engine: Arc<dyn IoEngine>) -> Self {
+        Self { engine, pos: 0 }
+    }
+
+    /// Moves the logical read position, mirroring `Seek::seek(SeekFrom::Start(pos))`
+    /// against the underlying engine.
+    fn seek(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Fills `buf` from the engine at the current position, advancing it by
+    /// `buf.len()`. Errors with an `UnexpectedEof` `Error::IO` if the engine
+    /// runs out of bytes before `buf` is full -- the same signal
+    /// `Read::read_exact` gives, which `scan_blocks` relies on to detect a
+    /// truncated file.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut read = 0;
+        while read < buf.len() {
+            let n = self.engine.read_at(self.pos, &mut buf[read..])?;
+            if n == 0 {
+                return Err(Error::IO(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                     "unexpected end of file")));
+            }
+            read += n;
+            self.pos += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Reads and validates the header, returning the position right after it
+    /// (== `HEADER_LEN`). Surfaces `Error::BadMagic`, `Error::TruncatedTransfer`
+    /// or `Error::UnsupportedVersion` as distinct failures -- see
+    /// [`TsmHeader`].
+    fn check_header(&mut self) -> Result<u64> {
+        self.seek(0);
+        let mut header = [0_u8; HEADER_LEN as usize];
+        self.read_exact(&mut header)?;
+        TsmHeader::decode(&header)?;
+        Ok(HEADER_LEN)
+    }
+
+    /// Reads the footer and returns `(index_offset, footer_offset)`, where
+    /// `footer_offset` is where the footer itself starts (i.e. where the
+    /// index ends) -- failing if `index_offset` is not strictly less than
+    /// that.
+    pub(crate) fn read_index_offset(&mut self) -> Result<(u64, u64)> {
+        let file_len = self.engine.len()?;
+        if file_len < HEADER_LEN + 8 {
+            return Err(Error::ReadTsmErr { reason: "file too small for a footer".to_string() });
+        }
+        let footer_offset = file_len - 8;
+        self.seek(footer_offset);
+        let mut buf = [0_u8; 8];
+        self.read_exact(&mut buf)?;
+        let index_offset = u64::from_be_bytes(buf);
+        if index_offset >= footer_offset {
+            return Err(Error::ReadTsmErr {
+                reason: format!("index offset {} is not less than footer offset {}",
+                                 index_offset, footer_offset),
+            });
+        }
+        Ok((index_offset, footer_offset))
+    }
+
+    /// Walks every block between `HEADER_LEN` and `end_bound`, verifying its
+    /// CRCs as it goes. Stops early (without error) if the file turns out to
+    /// be truncated mid-block, which is exactly the partial-write/crash case
+    /// this subsystem exists to recover from.
+    pub(crate) fn scan_blocks(&mut self, end_bound: u64) -> Result<Vec<ScannedBlock>> {
+        let mut blocks = Vec::new();
+        let mut pos = HEADER_LEN;
+        while pos < end_bound {
+            match self.read_block_at(pos) {
+                Ok(block) => {
+                    pos += 9 + 16 + block.ts_buf.len() as u64 + block.data_buf.len() as u64;
+                    blocks.push(block);
+                },
+                Err(Error::IO(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(blocks)
+    }
+
+    pub(crate) fn read_block_at(&mut self, pos: u64) -> Result<ScannedBlock> {
+        self.seek(pos);
+        let fid = self.read_u64()?;
+        // A corrupt (not truncated) type byte doesn't affect where the rest
+        // of the block's fields live, so keep reading instead of failing the
+        // whole scan over it -- see `check`, which turns a `None` here into
+        // a `BlockIssue` rather than aborting.
+        let filed_type: Option<FieldType> = self.read_u8()?.try_into().ok();
+        let ts_crc = self.read_u32()?;
+        let ts_len = self.read_u32()? as usize;
+        let mut ts_buf = vec![0_u8; ts_len];
+        self.read_exact(&mut ts_buf)?;
+        let data_crc = self.read_u32()?;
+        let data_len = self.read_u32()? as usize;
+        let mut data_buf = vec![0_u8; data_len];
+        self.read_exact(&mut data_buf)?;
+
+        let crc_ok = ts_crc == crc32fast::hash(&ts_buf) && data_crc == crc32fast::hash(&data_buf);
+        Ok(ScannedBlock { offset: pos, fid, filed_type, ts_buf, data_buf, crc_ok })
+    }
+
+    /// Returns the file's current length.
+    pub(crate) fn file_len(&mut self) -> Result<u64> {
+        Ok(self.engine.len()?)
+    }
+
+    /// Hands back the underlying engine, e.g. so [`super::TsmRepairer`] can
+    /// keep writing to the same file after scanning it.
+    pub(crate) fn into_inner(self) -> Arc<dyn IoEngine> {
+        self.engine
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0_u8; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    /// Parses the index into `fid -> [FileBlock]`, sorted by `min_ts`.
+    ///
+    /// A series' entries may be spread across more than one `[fid][type]
+    /// [count]` group -- [`super::writer::IndexedWriter`] spills its pending
+    /// index to disk in several such groups rather than one -- so groups for
+    /// the same `fid` are merged, not overwritten.
+    pub(crate) fn parse_index(&mut self,
+                               index_offset: u64,
+                               footer_offset: u64)
+                               -> Result<HashMap<u64, Vec<FileBlock>>> {
+        self.seek(index_offset);
+        let mut index: HashMap<u64, Vec<FileBlock>> = HashMap::new();
+        let mut pos = index_offset;
+        while pos < footer_offset {
+            let fid = self.read_u64()?;
+            let typ: FieldType = self.read_u8()?.try_into()?;
+            let cnt = self.read_u16()?;
+            let mut blocks = Vec::with_capacity(cnt as usize);
+            for _ in 0..cnt {
+                let min_ts = self.read_u64()? as i64;
+                let max_ts = self.read_u64()? as i64;
+                let offset = self.read_u64()?;
+                let size = self.read_u64()?;
+                blocks.push(FileBlock { min_ts,
+                                        max_ts,
+                                        offset,
+                                        filed_type: typ,
+                                        size,
+                                        reader_idx: 0 });
+            }
+            pos += 8 + 1 + 2 + (cnt as u64) * 32;
+            index.entry(fid).or_default().extend(blocks);
+        }
+        for blocks in index.values_mut() {
+            blocks.sort_by_key(|b| b.min_ts);
+        }
+        Ok(index)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let mut buf = [0_u8; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0_u8; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0_u8; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    /// Validates the header, every block's CRCs, and that every index entry
+    /// resolves to a block whose decoded time range matches. Never mutates
+    /// the file; use [`TsmRepairer`](super::TsmRepairer) to fix what's found.
+    pub fn check(&mut self) -> Result<CheckReport> {
+        self.check_header()?;
+        let (index_offset, footer_offset) = self.read_index_offset()?;
+        let blocks = self.scan_blocks(index_offset)?;
+        let blocks_by_offset: HashMap<u64, &ScannedBlock> =
+            blocks.iter().map(|b| (b.offset, b)).collect();
+
+        let mut report = CheckReport { blocks_checked: blocks.len(), issues: Vec::new() };
+        for block in &blocks {
+            if !block.crc_ok {
+                report.issues.push(BlockIssue::CrcMismatch { offset: block.offset });
+            }
+            if block.filed_type.is_none() {
+                report.issues.push(BlockIssue::Unreadable {
+                    offset: block.offset,
+                    reason: "corrupt field type byte".to_string(),
+                });
+            }
+        }
+
+        let index = self.parse_index(index_offset, footer_offset)?;
+        for (fid, blocks) in index.iter() {
+            for entry in blocks {
+                match blocks_by_offset.get(&entry.offset) {
+                    None => {
+                        report.issues.push(BlockIssue::Unreadable {
+                            offset: entry.offset,
+                            reason: "no block found at indexed offset".to_string(),
+                        });
+                    },
+                    Some(scanned) => {
+                        if scanned.fid != *fid {
+                            report.issues.push(BlockIssue::FidMismatch {
+                                offset: entry.offset,
+                                index_fid: *fid,
+                                block_fid: scanned.fid,
+                            });
+                        }
+                        match DataBlock::decode(entry.filed_type, &scanned.ts_buf, &scanned.data_buf)
+                        {
+                            Ok(decoded) => {
+                                let actual_range = decoded.time_range(0, decoded.len());
+                                if actual_range != (entry.min_ts, entry.max_ts) {
+                                    report.issues.push(BlockIssue::TimeRangeMismatch {
+                                        offset: entry.offset,
+                                        index_range: (entry.min_ts, entry.max_ts),
+                                        actual_range,
+                                    });
+                                }
+                            },
+                            Err(e) => {
+                                report.issues.push(BlockIssue::Unreadable {
+                                    offset: entry.offset,
+                                    reason: e.to_string(),
+                                });
+                            },
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::tsm::writer::{FooterBuilder, IndexedWriter, TsmBlockWriter, TsmIndexBuilder};
+    use crate::DirectIoEngine;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cnosdb_tsm_checker_test_{}_{}", std::process::id(), name))
+    }
+
+    fn open_rw(path: &std::path::Path) -> Arc<dyn IoEngine> {
+        let file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+        Arc::new(DirectIoEngine::new(file))
+    }
+
+    fn open_ro(path: &std::path::Path) -> Arc<dyn IoEngine> {
+        Arc::new(DirectIoEngine::new(OpenOptions::new().read(true).open(path).unwrap()))
+    }
+
+    #[test]
+    fn check_reports_no_issues_for_a_healthy_file() {
+        let path = temp_path("healthy");
+        let mut writer = IndexedWriter::new(open_rw(&path)).unwrap();
+        writer.write_block(1, DataBlock::Int { ts: vec![1, 2, 3], val: vec![10, 20, 30] })
+              .unwrap();
+        writer.finish().unwrap();
+
+        let mut checker = TsmChecker::new(open_ro(&path));
+        let report = checker.check().unwrap();
+        assert!(report.is_healthy());
+        assert_eq!(report.blocks_checked, 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn check_flags_a_corrupted_type_byte_without_aborting() {
+        let path = temp_path("corrupt_type");
+        let mut writer = IndexedWriter::new(open_rw(&path)).unwrap();
+        writer.write_block(1, DataBlock::Int { ts: vec![1, 2], val: vec![10, 20] }).unwrap();
+        writer.write_block(2, DataBlock::Int { ts: vec![3, 4], val: vec![30, 40] }).unwrap();
+        writer.finish().unwrap();
+
+        // Corrupt the first block's type byte (right after its 8-byte fid).
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        DirectIoEngine::new(file).write_at(HEADER_LEN + 8, &[0xFF]).unwrap();
+
+        let mut checker = TsmChecker::new(open_ro(&path));
+        let report = checker.check().unwrap();
+        assert_eq!(report.blocks_checked, 2);
+        assert!(report.issues.iter().any(
+            |i| matches!(i, BlockIssue::Unreadable { offset, .. } if *offset == HEADER_LEN)
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn check_flags_an_index_entry_shuffled_onto_the_wrong_series() {
+        let path = temp_path("fid_mismatch");
+        let mut block_writer = TsmBlockWriter::new(open_rw(&path), 0);
+        let file_blocks =
+            block_writer.build(1, DataBlock::Int { ts: vec![1, 2], val: vec![10, 20] }).unwrap();
+
+        // Record the block (written under fid 1) in the index under fid 2.
+        let mut index = HashMap::new();
+        index.insert(2_u64, file_blocks);
+        let (engine, pos) = block_writer.into_parts();
+        let mut index_builder = TsmIndexBuilder::new(engine, pos);
+        let index_offset = index_builder.build(index).unwrap();
+        let (engine, pos) = index_builder.into_parts();
+        FooterBuilder::new(engine, pos).build(index_offset).unwrap();
+
+        let mut checker = TsmChecker::new(open_ro(&path));
+        let report = checker.check().unwrap();
+        assert!(report.issues.iter().any(|i| {
+            matches!(i, BlockIssue::FidMismatch { index_fid: 2, block_fid: 1, .. })
+        }));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
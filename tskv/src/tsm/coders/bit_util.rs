@@ -0,0 +1,79 @@
+use crate::error::{Error, Result};
+
+/// A big-endian, MSB-first bit-level output buffer, used by codecs (Gorilla
+/// floats, boolean bit-packing) whose unit of work is smaller than a byte.
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    buf: Vec<u8>,
+    cur: u8,
+    /// Number of valid bits already written into `cur`, from the MSB down.
+    nbits: u8,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_bit(&mut self, bit: bool) {
+        self.cur |= (bit as u8) << (7 - self.nbits);
+        self.nbits += 1;
+        if self.nbits == 8 {
+            self.buf.push(self.cur);
+            self.cur = 0;
+            self.nbits = 0;
+        }
+    }
+
+    /// Pushes the low `nbits` of `value`, most-significant bit first.
+    pub fn push_bits(&mut self, value: u64, nbits: u8) {
+        for i in (0..nbits).rev() {
+            self.push_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Flushes any partial byte (zero-padded) and returns the buffer.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.buf.push(self.cur);
+        }
+        self.buf
+    }
+}
+
+/// The read-side counterpart of [`BitWriter`].
+pub(crate) struct BitReader<'a> {
+    buf: &'a [u8],
+    byte_pos: usize,
+    bit_pos: u8,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, byte_pos: 0, bit_pos: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        let byte = *self.buf
+                        .get(self.byte_pos)
+                        .ok_or_else(|| Error::ReadTsmErr {
+                            reason: "truncated bit stream".to_string(),
+                        })?;
+        let bit = (byte >> (7 - self.bit_pos)) & 1 == 1;
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.byte_pos += 1;
+        }
+        Ok(bit)
+    }
+
+    /// Reads `nbits` (<= 64) bits, most-significant bit first.
+    pub fn read_bits(&mut self, nbits: u8) -> Result<u64> {
+        let mut value = 0_u64;
+        for _ in 0..nbits {
+            value = (value << 1) | (self.read_bit()? as u64);
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,61 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::tsm::coders::bit_util::{BitReader, BitWriter};
+
+const CODEC_BITPACK: u8 = 1;
+
+/// Encodes a run of booleans as `[codec: u8][count: u32][bits...]`, one bit
+/// per value.
+pub fn encode(values: &[bool], dst: &mut Vec<u8>) {
+    dst.push(CODEC_BITPACK);
+    dst.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    let mut bw = BitWriter::new();
+    for &v in values {
+        bw.push_bit(v);
+    }
+    dst.extend_from_slice(&bw.finish());
+}
+
+/// Decodes exactly `count` booleans previously produced by [`encode`].
+pub fn decode(src: &[u8], count: usize) -> Result<Vec<bool>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if src.len() < 5 {
+        return Err(Error::ReadTsmErr { reason: "truncated boolean block".to_string() });
+    }
+    let codec = src[0];
+    if codec != CODEC_BITPACK {
+        return Err(Error::ReadTsmErr { reason: format!("unknown boolean codec: {}", codec) });
+    }
+    let stored_count = u32::from_be_bytes(src[1..5].try_into().unwrap()) as usize;
+    if stored_count != count {
+        return Err(Error::ReadTsmErr {
+            reason: format!("boolean count mismatch: index says {}, block says {}",
+                             count, stored_count),
+        });
+    }
+    let mut br = BitReader::new(&src[5..]);
+    (0..count).map(|_| br.read_bit()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_an_empty_block() {
+        let mut buf = Vec::new();
+        encode(&[], &mut buf);
+        assert_eq!(decode(&buf, 0).unwrap(), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn round_trips_a_mix_of_values() {
+        let values = vec![true, false, false, true, true, true, false];
+        let mut buf = Vec::new();
+        encode(&values, &mut buf);
+        assert_eq!(decode(&buf, values.len()).unwrap(), values);
+    }
+}
@@ -0,0 +1,133 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::tsm::coders::bit_util::{BitReader, BitWriter};
+
+const CODEC_GORILLA: u8 = 1;
+
+/// Encodes a run of floats as `[codec: u8][count: u32][...]` using Gorilla
+/// XOR compression: the first value is stored whole, and each later value
+/// is XORed with its predecessor -- a zero XOR (the common case for slowly
+/// changing metrics) costs a single bit, otherwise the leading/trailing
+/// zero window of the XOR is reused when possible or redefined with a few
+/// extra header bits when it isn't.
+pub fn encode(values: &[f64], dst: &mut Vec<u8>) {
+    dst.push(CODEC_GORILLA);
+    dst.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    if values.is_empty() {
+        return;
+    }
+
+    let mut bw = BitWriter::new();
+    let first = values[0].to_bits();
+    bw.push_bits(first, 64);
+
+    let mut prev = first;
+    // No window has been established yet, so the first non-zero XOR always
+    // takes the "new window" branch below.
+    let mut prev_leading: u32 = 65;
+    let mut prev_trailing: u32 = 65;
+    for &v in &values[1..] {
+        let cur = v.to_bits();
+        let xor = cur ^ prev;
+        if xor == 0 {
+            bw.push_bit(false);
+        } else {
+            bw.push_bit(true);
+            let leading = xor.leading_zeros().min(31);
+            let trailing = xor.trailing_zeros();
+            if leading >= prev_leading && trailing >= prev_trailing {
+                bw.push_bit(false);
+                let meaningful = 64 - prev_leading - prev_trailing;
+                bw.push_bits(xor >> prev_trailing, meaningful as u8);
+            } else {
+                bw.push_bit(true);
+                bw.push_bits(leading as u64, 5);
+                let meaningful = 64 - leading - trailing;
+                bw.push_bits((meaningful - 1) as u64, 6);
+                bw.push_bits(xor >> trailing, meaningful as u8);
+                prev_leading = leading;
+                prev_trailing = trailing;
+            }
+        }
+        prev = cur;
+    }
+    dst.extend_from_slice(&bw.finish());
+}
+
+/// Decodes exactly `count` floats previously produced by [`encode`].
+pub fn decode(src: &[u8], count: usize) -> Result<Vec<f64>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if src.len() < 5 {
+        return Err(Error::ReadTsmErr { reason: "truncated float block".to_string() });
+    }
+    let codec = src[0];
+    if codec != CODEC_GORILLA {
+        return Err(Error::ReadTsmErr { reason: format!("unknown float codec: {}", codec) });
+    }
+    let stored_count = u32::from_be_bytes(src[1..5].try_into().unwrap()) as usize;
+    if stored_count != count {
+        return Err(Error::ReadTsmErr {
+            reason: format!("float count mismatch: index says {}, block says {}",
+                             count, stored_count),
+        });
+    }
+
+    let mut br = BitReader::new(&src[5..]);
+    let first = br.read_bits(64)?;
+    let mut values = Vec::with_capacity(count);
+    values.push(f64::from_bits(first));
+
+    let mut prev = first;
+    let mut prev_leading: u32 = 65;
+    let mut prev_trailing: u32 = 65;
+    for _ in 1..count {
+        let cur = if !br.read_bit()? {
+            prev
+        } else if !br.read_bit()? {
+            let meaningful = 64 - prev_leading - prev_trailing;
+            let bits = br.read_bits(meaningful as u8)?;
+            prev ^ (bits << prev_trailing)
+        } else {
+            let leading = br.read_bits(5)? as u32;
+            let meaningful = br.read_bits(6)? as u32 + 1;
+            let trailing = 64 - leading - meaningful;
+            let bits = br.read_bits(meaningful as u8)?;
+            prev_leading = leading;
+            prev_trailing = trailing;
+            prev ^ (bits << trailing)
+        };
+        values.push(f64::from_bits(cur));
+        prev = cur;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_an_empty_block() {
+        let mut buf = Vec::new();
+        encode(&[], &mut buf);
+        assert_eq!(decode(&buf, 0).unwrap(), Vec::<f64>::new());
+    }
+
+    #[test]
+    fn round_trips_slowly_changing_values() {
+        let values = vec![1.0, 1.0, 1.5, 1.5, 1.5, -3.25, 42.0];
+        let mut buf = Vec::new();
+        encode(&values, &mut buf);
+        assert_eq!(decode(&buf, values.len()).unwrap(), values);
+    }
+
+    #[test]
+    fn count_mismatch_is_an_error() {
+        let mut buf = Vec::new();
+        encode(&[1.0, 2.0], &mut buf);
+        assert!(decode(&buf, 3).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+pub(crate) mod bit_util;
+pub mod boolean;
+pub mod float;
+pub mod integer;
+pub(crate) mod simple8b;
+pub mod string;
+pub mod timestamp;
+pub mod unsigned;
+pub(crate) mod zigzag;
@@ -0,0 +1,79 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+
+/// `(values per word, bits per value)` for each of the 16 selectors a
+/// simple8b word can hold in its top 4 bits. Selectors 0/1 are pure
+/// run-length markers for zero runs (no data bits); the rest pack `n`
+/// fixed-width values into the word's low 60 bits.
+const SELECTORS: [(u32, u32); 16] = [(240, 0), (120, 0), (60, 1), (30, 2), (20, 3), (15, 4),
+                                      (12, 5), (10, 6), (8, 7), (7, 8), (6, 10), (5, 12),
+                                      (4, 15), (3, 20), (2, 30), (1, 60)];
+
+fn fits(values: &[u64], bits: u32) -> bool {
+    if bits == 0 {
+        return values.iter().all(|&v| v == 0);
+    }
+    let max = (1_u64 << bits) - 1;
+    values.iter().all(|&v| v <= max)
+}
+
+/// Packs `values` into 8-byte simple8b words. Returns `None` if any single
+/// value needs more than 60 bits, in which case the caller should fall back
+/// to storing the values uncompressed.
+pub(crate) fn encode(values: &[u64]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let remaining = values.len() - i;
+        let mut packed = false;
+        for (selector, &(sel_n, bits)) in SELECTORS.iter().enumerate() {
+            let n = (sel_n as usize).min(remaining);
+            // A selector is only used at its full width, or to consume
+            // exactly what's left of the input (a short final word).
+            if n == 0 || (n != sel_n as usize && n != remaining) {
+                continue;
+            }
+            if !fits(&values[i..i + n], bits) {
+                continue;
+            }
+            let mut word = (selector as u64) << 60;
+            for (j, &v) in values[i..i + n].iter().enumerate() {
+                word |= v << (j as u32 * bits);
+            }
+            out.extend_from_slice(&word.to_be_bytes());
+            i += n;
+            packed = true;
+            break;
+        }
+        if !packed {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+/// Unpacks exactly `count` values previously produced by [`encode`].
+pub(crate) fn decode(buf: &[u8], count: usize) -> Result<Vec<u64>> {
+    let mut values = Vec::with_capacity(count);
+    let mut pos = 0;
+    while values.len() < count {
+        if pos + 8 > buf.len() {
+            return Err(Error::ReadTsmErr { reason: "truncated simple8b block".to_string() });
+        }
+        let word = u64::from_be_bytes(buf[pos..pos + 8].try_into().unwrap());
+        pos += 8;
+        let selector = (word >> 60) as usize;
+        let (n, bits) = SELECTORS[selector];
+        if bits == 0 {
+            values.extend(std::iter::repeat(0_u64).take(n as usize));
+        } else {
+            let mask = (1_u64 << bits) - 1;
+            for j in 0..n {
+                values.push((word >> (j * bits)) & mask);
+            }
+        }
+    }
+    values.truncate(count);
+    Ok(values)
+}
@@ -0,0 +1,153 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::tsm::coders::{simple8b, zigzag};
+
+const CODEC_RAW: u8 = 0;
+const CODEC_DELTA_OF_DELTA: u8 = 1;
+
+/// Encodes timestamps as `[codec: u8][count: u32][...]`. The primary codec
+/// stores the first timestamp and first delta raw, then zigzag + simple8b
+/// packs the deltas-of-deltas of everything after -- the usual near-zero
+/// pattern for a regular scrape interval. Falls back to raw 64-bit
+/// timestamps if a delta-of-delta doesn't fit a simple8b word.
+pub fn encode(ts: &[i64], dst: &mut Vec<u8>) {
+    let header_at = dst.len();
+    dst.push(CODEC_DELTA_OF_DELTA);
+    dst.extend_from_slice(&(ts.len() as u32).to_be_bytes());
+
+    if ts.len() < 2 {
+        for &v in ts {
+            dst.extend_from_slice(&v.to_be_bytes());
+        }
+        return;
+    }
+
+    let first = ts[0];
+    let first_delta = ts[1] - ts[0];
+    let mut prev_delta = first_delta;
+    let mut dod = Vec::with_capacity(ts.len() - 2);
+    for w in ts[1..].windows(2) {
+        let delta = w[1] - w[0];
+        dod.push(zigzag::encode(delta - prev_delta));
+        prev_delta = delta;
+    }
+
+    match simple8b::encode(&dod) {
+        Some(packed) => {
+            dst.extend_from_slice(&first.to_be_bytes());
+            dst.extend_from_slice(&first_delta.to_be_bytes());
+            dst.extend_from_slice(&packed);
+        },
+        None => {
+            dst[header_at] = CODEC_RAW;
+            for &v in ts {
+                dst.extend_from_slice(&v.to_be_bytes());
+            }
+        },
+    }
+}
+
+/// Reads back the value count a [`encode`]d buffer carries in its header,
+/// without decoding the rest. Lets a caller that doesn't already know the
+/// block's length (e.g. [`crate::tsm::DataBlock::decode`], which derives
+/// every other column's count from the timestamp column) bootstrap it.
+pub fn decode_count(src: &[u8]) -> Result<usize> {
+    if src.len() < 5 {
+        return Err(Error::ReadTsmErr { reason: "truncated timestamp block".to_string() });
+    }
+    Ok(u32::from_be_bytes(src[1..5].try_into().unwrap()) as usize)
+}
+
+/// Decodes exactly `count` timestamps previously produced by [`encode`].
+pub fn decode(src: &[u8], count: usize) -> Result<Vec<i64>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if src.len() < 5 {
+        return Err(Error::ReadTsmErr { reason: "truncated timestamp block".to_string() });
+    }
+    let codec = src[0];
+    let stored_count = u32::from_be_bytes(src[1..5].try_into().unwrap()) as usize;
+    if stored_count != count {
+        return Err(Error::ReadTsmErr {
+            reason: format!("timestamp count mismatch: index says {}, block says {}",
+                             count, stored_count),
+        });
+    }
+    let body = &src[5..];
+
+    if count < 2 {
+        if body.len() < count * 8 {
+            return Err(Error::ReadTsmErr { reason: "corrupt raw timestamp block".to_string() });
+        }
+        return Ok(body.chunks_exact(8)
+                       .take(count)
+                       .map(|c| i64::from_be_bytes(c.try_into().unwrap()))
+                       .collect());
+    }
+
+    match codec {
+        CODEC_RAW => {
+            if body.len() != count * 8 {
+                return Err(Error::ReadTsmErr { reason: "corrupt raw timestamp block".to_string() });
+            }
+            Ok(body.chunks_exact(8).map(|c| i64::from_be_bytes(c.try_into().unwrap())).collect())
+        },
+        CODEC_DELTA_OF_DELTA => {
+            if body.len() < 16 {
+                return Err(Error::ReadTsmErr {
+                    reason: "corrupt delta-of-delta timestamp block".to_string(),
+                });
+            }
+            let first = i64::from_be_bytes(body[0..8].try_into().unwrap());
+            let first_delta = i64::from_be_bytes(body[8..16].try_into().unwrap());
+            let dod = simple8b::decode(&body[16..], count - 2)?;
+
+            let mut ts = Vec::with_capacity(count);
+            ts.push(first);
+            ts.push(first + first_delta);
+            let mut prev_delta = first_delta;
+            for d in dod {
+                let delta = prev_delta + zigzag::decode(d);
+                let next = ts.last().copied().unwrap() + delta;
+                ts.push(next);
+                prev_delta = delta;
+            }
+            Ok(ts)
+        },
+        _ => Err(Error::ReadTsmErr { reason: format!("unknown timestamp codec: {}", codec) }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_regular_interval() {
+        let ts = vec![1000_i64, 2000, 3000, 4000, 5000];
+        let mut buf = Vec::new();
+        encode(&ts, &mut buf);
+        assert_eq!(buf[0], CODEC_DELTA_OF_DELTA);
+        assert_eq!(decode_count(&buf).unwrap(), ts.len());
+        assert_eq!(decode(&buf, ts.len()).unwrap(), ts);
+    }
+
+    #[test]
+    fn falls_back_to_raw_when_a_delta_of_delta_does_not_fit_a_simple8b_word() {
+        let ts = vec![0_i64, 1, i64::MAX];
+        let mut buf = Vec::new();
+        encode(&ts, &mut buf);
+        assert_eq!(buf[0], CODEC_RAW);
+        assert_eq!(decode(&buf, ts.len()).unwrap(), ts);
+    }
+
+    #[test]
+    fn round_trips_fewer_than_two_values() {
+        let ts = vec![42_i64];
+        let mut buf = Vec::new();
+        encode(&ts, &mut buf);
+        assert_eq!(decode(&buf, ts.len()).unwrap(), ts);
+    }
+}
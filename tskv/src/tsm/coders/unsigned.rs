@@ -0,0 +1,79 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::tsm::coders::simple8b;
+
+const CODEC_RAW: u8 = 0;
+const CODEC_SIMPLE8B: u8 = 1;
+
+/// Encodes a run of unsigned integers as `[codec: u8][count: u32][...]`,
+/// simple8b-packed; falls back to raw 64-bit values if a value doesn't fit
+/// a simple8b word.
+pub fn encode(values: &[u64], dst: &mut Vec<u8>) {
+    let header_at = dst.len();
+    dst.push(CODEC_SIMPLE8B);
+    dst.extend_from_slice(&(values.len() as u32).to_be_bytes());
+
+    match simple8b::encode(values) {
+        Some(packed) => dst.extend_from_slice(&packed),
+        None => {
+            dst[header_at] = CODEC_RAW;
+            for &v in values {
+                dst.extend_from_slice(&v.to_be_bytes());
+            }
+        },
+    }
+}
+
+/// Decodes exactly `count` unsigned integers previously produced by
+/// [`encode`].
+pub fn decode(src: &[u8], count: usize) -> Result<Vec<u64>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if src.len() < 5 {
+        return Err(Error::ReadTsmErr { reason: "truncated unsigned block".to_string() });
+    }
+    let codec = src[0];
+    let stored_count = u32::from_be_bytes(src[1..5].try_into().unwrap()) as usize;
+    if stored_count != count {
+        return Err(Error::ReadTsmErr {
+            reason: format!("unsigned count mismatch: index says {}, block says {}",
+                             count, stored_count),
+        });
+    }
+    let body = &src[5..];
+    match codec {
+        CODEC_RAW => {
+            if body.len() != count * 8 {
+                return Err(Error::ReadTsmErr { reason: "corrupt raw unsigned block".to_string() });
+            }
+            Ok(body.chunks_exact(8).map(|c| u64::from_be_bytes(c.try_into().unwrap())).collect())
+        },
+        CODEC_SIMPLE8B => simple8b::decode(body, count),
+        _ => Err(Error::ReadTsmErr { reason: format!("unknown unsigned codec: {}", codec) }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_simple8b_packed_values() {
+        let values = vec![0_u64, 1, 42, 1000];
+        let mut buf = Vec::new();
+        encode(&values, &mut buf);
+        assert_eq!(buf[0], CODEC_SIMPLE8B);
+        assert_eq!(decode(&buf, values.len()).unwrap(), values);
+    }
+
+    #[test]
+    fn falls_back_to_raw_when_a_value_does_not_fit_a_simple8b_word() {
+        let values = vec![1_u64, u64::MAX];
+        let mut buf = Vec::new();
+        encode(&values, &mut buf);
+        assert_eq!(buf[0], CODEC_RAW);
+        assert_eq!(decode(&buf, values.len()).unwrap(), values);
+    }
+}
@@ -0,0 +1,9 @@
+/// Maps signed integers to unsigned ones so small magnitudes (positive or
+/// negative) stay small, which is what `simple8b` needs to pack them densely.
+pub(crate) fn encode(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+pub(crate) fn decode(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
@@ -0,0 +1,16 @@
+mod block;
+pub mod checker;
+pub mod coders;
+mod reader;
+mod repairer;
+mod writer;
+
+pub use block::*;
+pub use checker::*;
+pub use reader::*;
+pub use repairer::*;
+pub use writer::*;
+
+/// Maximum number of values a single on-disk block may hold before a
+/// [`DataBlock`] is split across multiple blocks.
+pub const MAX_BLOCK_VALUES: usize = 1000;
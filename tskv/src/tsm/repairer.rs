@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::tsm::checker::ScannedBlock;
+use crate::tsm::writer::{FooterBuilder, TsmIndexBuilder, HEADER_LEN};
+use crate::tsm::{DataBlock, FileBlock, TsmChecker};
+use crate::IoEngine;
+
+/// Outcome of [`TsmRepairer::repair`]: how many blocks were kept and how
+/// many had to be dropped because their CRC no longer verified or they could
+/// not be decoded.
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub blocks_salvaged: usize,
+    pub blocks_dropped: usize,
+}
+
+/// Rebuilds a TSM file's index by re-scanning its blocks from scratch,
+/// ignoring whatever the (possibly corrupt or missing) index and footer
+/// currently say, and salvaging every block whose CRC still verifies. This
+/// recovers a file after a partial write or crash, at the cost of any block
+/// whose bytes themselves were damaged.
+pub struct TsmRepairer {
+    checker: TsmChecker,
+}
+
+impl TsmRepairer {
+    pub fn new(engine: Arc<dyn IoEngine>) -> Self {
+        Self { checker: TsmChecker::new(engine) }
+    }
+
+    pub fn repair(mut self) -> Result<RepairReport> {
+        let file_len = self.checker.file_len()?;
+        let scanned = self.checker.scan_blocks(file_len)?;
+
+        let mut report = RepairReport::default();
+        let mut by_fid: HashMap<u64, Vec<FileBlock>> = HashMap::new();
+        for block in &scanned {
+            if !block.crc_ok {
+                report.blocks_dropped += 1;
+                continue;
+            }
+            match Self::rebuild_entry(block) {
+                Ok(entry) => {
+                    report.blocks_salvaged += 1;
+                    by_fid.entry(block.fid).or_default().push(entry);
+                },
+                Err(_) => report.blocks_dropped += 1,
+            }
+        }
+        for entries in by_fid.values_mut() {
+            entries.sort_by_key(|b| b.min_ts);
+        }
+
+        // The rebuilt index starts right after the last salvaged block;
+        // anything past that in the old file is stale index/footer bytes.
+        let index_offset = scanned.iter()
+                                   .filter(|b| b.crc_ok)
+                                   .map(|b| {
+                                       b.offset + 9 + 16 + b.ts_buf.len() as u64
+                                       + b.data_buf.len() as u64
+                                   })
+                                   .max()
+                                   .unwrap_or(HEADER_LEN);
+
+        let engine = self.checker.into_inner();
+        let mut index_builder = TsmIndexBuilder::new(engine, index_offset);
+        let index_pos = index_builder.build(by_fid)?;
+        let (engine, pos) = index_builder.into_parts();
+        FooterBuilder::new(engine, pos).build(index_pos)?;
+
+        Ok(report)
+    }
+
+    /// Recomputes a block's `min_ts`/`max_ts` by decoding it, independent of
+    /// whatever the (possibly corrupt) index used to say about it. A block
+    /// whose type byte didn't decode to a known [`crate::FieldType`] can't be
+    /// salvaged even if its CRC checks out, since there's no way to know how
+    /// to decode its payload.
+    fn rebuild_entry(block: &ScannedBlock) -> Result<FileBlock> {
+        let filed_type = block.filed_type.ok_or_else(|| Error::ReadTsmErr {
+            reason: format!("corrupt field type byte at offset {}", block.offset),
+        })?;
+        let decoded = DataBlock::decode(filed_type, &block.ts_buf, &block.data_buf)?;
+        let (min_ts, max_ts) = decoded.time_range(0, decoded.len());
+        Ok(FileBlock { min_ts,
+                       max_ts,
+                       offset: block.offset,
+                       filed_type,
+                       size: 9 + 16 + block.ts_buf.len() as u64 + block.data_buf.len() as u64,
+                       reader_idx: 0 })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::tsm::writer::TsmBlockWriter;
+    use crate::tsm::TsmReader;
+    use crate::DirectIoEngine;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cnosdb_tsm_repairer_test_{}_{}", std::process::id(), name))
+    }
+
+    fn open_rw(path: &std::path::Path) -> Arc<dyn IoEngine> {
+        let file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+        Arc::new(DirectIoEngine::new(file))
+    }
+
+    /// Like `open_rw`, but without `truncate`, for reopening a file whose
+    /// contents must survive the reopen (e.g. to repair it).
+    fn open_existing_rw(path: &std::path::Path) -> Arc<dyn IoEngine> {
+        Arc::new(DirectIoEngine::new(OpenOptions::new().read(true).write(true).open(path).unwrap()))
+    }
+
+    fn open_ro(path: &std::path::Path) -> Arc<dyn IoEngine> {
+        Arc::new(DirectIoEngine::new(OpenOptions::new().read(true).open(path).unwrap()))
+    }
+
+    #[test]
+    fn repair_rebuilds_the_index_after_a_crash_before_it_was_written() {
+        let path = temp_path("no_index");
+        let mut block_writer = TsmBlockWriter::new(open_rw(&path), 0);
+        block_writer.build(1, DataBlock::Int { ts: vec![1, 2], val: vec![10, 20] }).unwrap();
+        block_writer.build(2, DataBlock::Int { ts: vec![3, 4], val: vec![30, 40] }).unwrap();
+        drop(block_writer);
+
+        let report = TsmRepairer::new(open_existing_rw(&path)).repair().unwrap();
+        assert_eq!(report.blocks_salvaged, 2);
+        assert_eq!(report.blocks_dropped, 0);
+
+        let mut reader = TsmReader::new(open_ro(&path)).unwrap();
+        assert_eq!(reader.query(1, 0, 10).unwrap().count(), 1);
+        assert_eq!(reader.query(2, 0, 10).unwrap().count(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn repair_drops_a_block_whose_crc_no_longer_verifies() {
+        let path = temp_path("bad_crc");
+        let mut block_writer = TsmBlockWriter::new(open_rw(&path), 0);
+        block_writer.build(1, DataBlock::Int { ts: vec![1, 2], val: vec![10, 20] }).unwrap();
+        drop(block_writer);
+
+        // Corrupt two bytes of the ts buffer, right after [fid: 8][type: 1]
+        // [ts_crc: 4][ts_len: 4].
+        let engine = open_existing_rw(&path);
+        engine.write_at(HEADER_LEN + 17, &[0xFF, 0xFF]).unwrap();
+        drop(engine);
+
+        let report = TsmRepairer::new(open_existing_rw(&path)).repair().unwrap();
+        assert_eq!(report.blocks_salvaged, 0);
+        assert_eq!(report.blocks_dropped, 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,36 +1,43 @@
 use std::{
     collections::HashMap,
-    io::{Seek, SeekFrom, Write},
+    fs::OpenOptions,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
 };
 
 use super::{block, MAX_BLOCK_VALUES};
-use crate::{
-    error::{Error, Result},
-    DataBlock, FileBlock, FileCursor,
-};
+use crate::{error::Result, DataBlock, DirectIoEngine, FileBlock, IoEngine, TsmHeader};
 
 // A TSM file is composed for four sections: header, blocks, index and the footer.
 //
-// ┌────────┬────────────────────────────────────┬─────────────┬──────────────┐
-// │ Header │               Blocks               │    Index    │    Footer    │
-// │5 bytes │              N bytes               │   N bytes   │   4 bytes    │
-// └────────┴────────────────────────────────────┴─────────────┴──────────────┘
+// ┌─────────┬───────────────────────────────────┬─────────────┬──────────────┐
+// │ Header  │               Blocks              │    Index    │    Footer    │
+// │13 bytes │              N bytes               │   N bytes   │   8 bytes    │
+// └─────────┴───────────────────────────────────┴─────────────┴──────────────┘
+//
+// ┌───────────────────────────────┐
+// │             Header            │
+// ├─────────┬─────────┬───────────┤
+// │Signature│ Version │Feature Fl.│
+// │ 8 bytes │ 1 byte  │  4 bytes  │
+// └─────────┴─────────┴───────────┘
 //
-// ┌───────────────────┐
-// │      Header       │
-// ├─────────┬─────────┤
-// │  Magic  │ Version │
-// │ 4 bytes │ 1 byte  │
-// └─────────┴─────────┘
+// See `TsmHeader` for why the signature is 8 PNG-style bytes rather than a
+// bare magic number.
 //
-// ┌───────────────────────────────────────┐
-// │               Blocks                  │
-// ├───────────────────┬───────────────────┤
-// │                Block                  │
-// ├─────────┬─────────┼─────────┬─────────┼
-// │  CRC    │ ts      │  CRC    │  value  │
-// │ 4 bytes │ N bytes │ 4 bytes │ N bytes │
-// └─────────┴─────────┴─────────┴─────────┴
+// ┌───────────────────────────────────────────────────────────────────────────────┐
+// │                                     Blocks                                     │
+// ├───────────────────────────────────────────────────────────────────────────┬───┤
+// │                                    Block                                   │...│
+// ├────────┬──────┬─────────┬─────────┬─────────┼─────────┬─────────┬─────────┼───┤
+// │ fieldId│ Type │  CRC    │ ts_len  │ ts      │  CRC    │data_len │  value  │   │
+// │8 bytes │1 byte│ 4 bytes │ 4 bytes │ N bytes │ 4 bytes │ 4 bytes │ N bytes │   │
+// └────────┴──────┴─────────┴─────────┴─────────┴─────────┴─────────┴─────────┴───┘
+//
+// Each block carries its own series id, type and length prefixes so a reader
+// can walk them sequentially from HEADER_LEN without consulting the index,
+// and a corrupt index can be rebuilt purely by re-scanning the blocks (see
+// `tsm::checker` / `tsm::repairer`).
 //
 //  ──────────────────────────────────────────────────────────────────┐
 // │                                   Index                          │
@@ -46,40 +53,51 @@ use crate::{
 // │ 8 bytes │
 // └─────────┘
 
-const HEADER_LEN: u64 = 5;
-const TSM_MAGIC: u32 = 0x1346613;
-const VERSION: u8 = 1;
+pub(crate) use crate::file_header::FILE_HEADER_LEN as HEADER_LEN;
 
 pub struct FooterBuilder {
-    writer: FileCursor,
+    engine: Arc<dyn IoEngine>,
+    pos: u64,
 }
 impl FooterBuilder {
-    pub fn new(writer: FileCursor) -> Self {
-        Self { writer }
+    pub fn new(engine: Arc<dyn IoEngine>, pos: u64) -> Self {
+        Self { engine, pos }
     }
     pub fn build(&mut self, offset: u64) -> Result<()> {
-        self.writer
-            .write(&mut offset.to_be_bytes().to_vec())
-            .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
+        self.engine.write_at(self.pos, &offset.to_be_bytes())?;
+        self.pos += 8;
         Ok(())
     }
+
+    /// Hands back the underlying engine and position once the footer has
+    /// been written.
+    pub(crate) fn into_parts(self) -> (Arc<dyn IoEngine>, u64) {
+        (self.engine, self.pos)
+    }
 }
 pub struct TsmIndexBuilder {
-    writer: FileCursor,
+    engine: Arc<dyn IoEngine>,
+    pos: u64,
 }
 
 impl TsmIndexBuilder {
-    pub fn new(writer: FileCursor) -> Self {
-        Self { writer }
+    pub fn new(engine: Arc<dyn IoEngine>, pos: u64) -> Self {
+        Self { engine, pos }
+    }
+
+    /// Hands back the underlying engine and position once the index has
+    /// been written, so the caller can keep writing (e.g. the footer) to the
+    /// same file.
+    pub(crate) fn into_parts(self) -> (Arc<dyn IoEngine>, u64) {
+        (self.engine, self.pos)
     }
 
     pub fn build(&mut self, indexs: HashMap<u64, Vec<FileBlock>>) -> Result<u64> {
-        let res = self.writer.pos();
+        let res = self.pos;
         for (fid, blks) in indexs {
             let mut buf = Vec::new();
             let block = blks.first().unwrap();
-            // let typ:u8 = block.filed_type.into();
-            let typ: u8 = 1;
+            let typ: u8 = block.filed_type.into();
             let cnt: u16 = blks.len() as u16;
             buf.append(&mut fid.to_be_bytes().to_vec());
             buf.append(&mut typ.to_be_bytes().to_vec());
@@ -91,24 +109,37 @@ impl TsmIndexBuilder {
                 buf.append(&mut blk.offset.to_be_bytes().to_vec());
                 buf.append(&mut blk.size.to_be_bytes().to_vec());
             }
-            self.writer.write(&buf).map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
+            self.engine.write_at(self.pos, &buf)?;
+            self.pos += buf.len() as u64;
         }
         Ok(res)
     }
 }
 
 pub struct TsmBlockWriter {
-    writer: FileCursor,
+    engine: Arc<dyn IoEngine>,
+    pos: u64,
 }
 
 impl TsmBlockWriter {
-    pub fn new(writer: FileCursor) -> Self {
-        Self { writer }
+    pub fn new(engine: Arc<dyn IoEngine>, pos: u64) -> Self {
+        Self { engine, pos }
+    }
+
+    /// Hands back the underlying engine and position, e.g. so
+    /// [`IndexedWriter`] can keep writing the index and footer to the same
+    /// file.
+    pub(crate) fn into_parts(self) -> (Arc<dyn IoEngine>, u64) {
+        (self.engine, self.pos)
     }
 }
 
 impl TsmBlockWriter {
-    fn build(&mut self, mut block: DataBlock) -> Result<Vec<FileBlock>> {
+    /// Encodes `block` (the values of series `fid`) into one or more on-disk
+    /// blocks, each self-describing its owning series so a corrupt index can
+    /// be rebuilt by scanning the blocks alone (see `tsm::checker` /
+    /// `tsm::repairer`).
+    pub(crate) fn build(&mut self, fid: u64, mut block: DataBlock) -> Result<Vec<FileBlock>> {
         let filed_type = block.filed_type();
         let len = block.len();
         let n = (len - 1) / MAX_BLOCK_VALUES + 1;
@@ -121,37 +152,32 @@ impl TsmBlockWriter {
             last_index = end;
             let (min_ts, max_ts) = block.time_range(start, end);
             let (ts_buf, data_buf) = block.encode(start, end)?;
-            if self.writer.pos() <= HEADER_LEN {
-                let mut buf = Vec::with_capacity(HEADER_LEN as usize);
-                buf.append(&mut TSM_MAGIC.to_be_bytes().to_vec());
-                buf.append(&mut VERSION.to_be_bytes().to_vec());
-                self.writer
-                    .seek(SeekFrom::Start(0))
-                    .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
-                self.writer
-                    .write(&buf)
-                    .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
+            if self.pos <= HEADER_LEN {
+                let header = TsmHeader::new(0).encode();
+                self.engine.write_at(0, &header)?;
+                self.pos = HEADER_LEN;
             }
             // fill data if err occur reset the pos
-            let offset = self.writer.pos();
-            self.writer
-                .write(&mut crc32fast::hash(&ts_buf).to_be_bytes().to_vec())
-                .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
-            self.writer
-                .write(&ts_buf)
-                .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
-            self.writer
-                .write(&mut crc32fast::hash(&data_buf).to_be_bytes().to_vec())
-                .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
-            self.writer
-                .write(&data_buf)
-                .map_err(|e| Error::WriteTsmErr { reason: e.to_string() })?;
-            let size = ts_buf.len() + data_buf.len();
+            let offset = self.pos;
+            let mut buf = Vec::with_capacity(9 + 16 + ts_buf.len() + data_buf.len());
+            buf.extend_from_slice(&fid.to_be_bytes());
+            buf.push(u8::from(filed_type));
+            buf.extend_from_slice(&crc32fast::hash(&ts_buf).to_be_bytes());
+            buf.extend_from_slice(&(ts_buf.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&ts_buf);
+            buf.extend_from_slice(&crc32fast::hash(&data_buf).to_be_bytes());
+            buf.extend_from_slice(&(data_buf.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&data_buf);
+            self.engine.write_at(offset, &buf)?;
+            self.pos += buf.len() as u64;
+            // Total on-disk footprint of the block, including the series
+            // header, CRCs and length prefixes, so both a reader and the
+            // repairer can read [offset, offset + size).
             res.push(FileBlock { min_ts,
                                  max_ts,
                                  offset,
                                  filed_type,
-                                 size: size as u64,
+                                 size: buf.len() as u64,
                                  reader_idx: 0 });
             i += 1;
         }
@@ -159,6 +185,107 @@ impl TsmBlockWriter {
     }
 }
 
+/// Number of buffered [`FileBlock`] entries (summed across all series) above
+/// which [`IndexedWriter`] spills its pending index entries to a scratch
+/// file, so peak memory stays bounded by this threshold no matter how much
+/// data has been written between `finish` calls.
+pub(crate) const INDEX_FLUSH_THRESHOLD: usize = 4096;
+
+static SCRATCH_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Picks a fresh path for an [`IndexedWriter`]'s scratch file. Unique per
+/// process and per writer, so concurrent writers never collide.
+fn scratch_index_path() -> std::path::PathBuf {
+    let n = SCRATCH_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("cnosdb-tsm-index-scratch-{}-{}", std::process::id(), n))
+}
+
+/// Combines [`TsmBlockWriter`] with incremental index bookkeeping: each call
+/// to [`IndexedWriter::write_block`] writes its blocks straight to disk, and
+/// the resulting [`FileBlock`] metadata is buffered only up to
+/// [`INDEX_FLUSH_THRESHOLD`] entries before being spilled to a scratch file
+/// (see [`IndexedWriter::flush_pending`]) -- unlike [`TsmIndexBuilder::build`]
+/// taken alone, which expects its whole index handed to it as one in-memory
+/// `HashMap`, an `IndexedWriter`'s own memory use never grows with the
+/// amount of data written. Call [`IndexedWriter::finish`] once to write the
+/// accumulated index and footer.
+pub struct IndexedWriter {
+    blocks: TsmBlockWriter,
+    pending: HashMap<u64, Vec<FileBlock>>,
+    pending_len: usize,
+    scratch_path: std::path::PathBuf,
+    scratch: TsmIndexBuilder,
+}
+
+impl IndexedWriter {
+    pub fn new(engine: Arc<dyn IoEngine>) -> Result<Self> {
+        let scratch_path = scratch_index_path();
+        let scratch_file = OpenOptions::new().read(true)
+                                              .write(true)
+                                              .create(true)
+                                              .truncate(true)
+                                              .open(&scratch_path)?;
+        Ok(Self { blocks: TsmBlockWriter::new(engine, 0),
+                  pending: HashMap::new(),
+                  pending_len: 0,
+                  scratch_path,
+                  scratch: TsmIndexBuilder::new(Arc::new(DirectIoEngine::new(scratch_file)), 0) })
+    }
+
+    /// Encodes and writes series `fid`'s `block`, folding the resulting
+    /// [`FileBlock`] entries into the pending index and spilling it to the
+    /// scratch file once it grows past [`INDEX_FLUSH_THRESHOLD`].
+    pub fn write_block(&mut self, fid: u64, block: DataBlock) -> Result<()> {
+        let file_blocks = self.blocks.build(fid, block)?;
+        self.pending_len += file_blocks.len();
+        self.pending.entry(fid).or_default().extend(file_blocks);
+        if self.pending_len >= INDEX_FLUSH_THRESHOLD {
+            self.flush_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the pending index entries to the scratch file and drops them
+    /// from memory. A series written to across more than one flush simply
+    /// ends up with more than one index group in the scratch file; see
+    /// [`TsmChecker::parse_index`](super::checker::TsmChecker::parse_index),
+    /// which merges them back together when reading.
+    fn flush_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.scratch.build(std::mem::take(&mut self.pending))?;
+        self.pending_len = 0;
+        Ok(())
+    }
+
+    /// Writes the accumulated index and footer, consuming the writer. The
+    /// spilled index is copied over from the scratch file in fixed-size
+    /// chunks rather than read back into memory whole.
+    pub fn finish(mut self) -> Result<Arc<dyn IoEngine>> {
+        self.flush_pending()?;
+        let (scratch_engine, scratch_len) = self.scratch.into_parts();
+
+        let (engine, index_offset) = self.blocks.into_parts();
+        let mut chunk = vec![0_u8; 64 * 1024];
+        let mut copied = 0_u64;
+        while copied < scratch_len {
+            let n = scratch_engine.read_at(copied, &mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            engine.write_at(index_offset + copied, &chunk[..n])?;
+            copied += n as u64;
+        }
+
+        let mut footer_builder = FooterBuilder::new(engine, index_offset + copied);
+        footer_builder.build(index_offset)?;
+        let _ = std::fs::remove_file(&self.scratch_path);
+        let (engine, _) = footer_builder.into_parts();
+        Ok(engine)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{tsm::coders, DataBlock, StrCell};